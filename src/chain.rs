@@ -0,0 +1,153 @@
+//! Reconstructs the traversal order implied by each message's `next_id`,
+//! rather than the incidental `id` ordering `BinaryHeap` gives us, and reports
+//! anything that makes the chain untrustworthy.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::Pipeline;
+
+/// Result of walking a [`Pipeline`]'s `next_id` chain.
+///
+/// `ordered_ids`/`ordered_bodies` are only a faithful reconstruction when
+/// `gaps`, `cycles` and `duplicate_ids` are empty and `heads` has exactly one
+/// entry; anomalies mean the walk stopped early, or started from the
+/// lowest-numbered head and then appended whatever it had not reached (the
+/// other heads' chains, or a head-less cycle) after the fact.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChainReport {
+    pub ordered_ids: Vec<u8>,
+    pub ordered_bodies: Vec<Vec<u8>>,
+    /// `(id, next_id)` pairs where `next_id` names a message we never received.
+    pub gaps: Vec<(u8, u8)>,
+    /// Each cycle found, as the ids involved in traversal order.
+    pub cycles: Vec<Vec<u8>>,
+    /// Ids never referenced as anyone's `next_id`. Exactly one is expected;
+    /// zero or more than one both indicate a broken pipeline.
+    pub heads: Vec<u8>,
+    pub duplicate_ids: Vec<u8>,
+}
+
+impl Pipeline {
+    pub fn reconstruct_chain(&self) -> ChainReport {
+        let mut by_id: HashMap<u8, &crate::Message> = HashMap::new();
+        let mut duplicate_ids = Vec::new();
+        for msg in self.message.iter() {
+            if by_id.insert(msg.id, msg).is_some() {
+                duplicate_ids.push(msg.id);
+            }
+        }
+
+        let referenced: HashSet<u8> = self.message.iter().filter_map(|msg| msg.next_id).collect();
+        let mut heads: Vec<u8> = by_id.keys().copied().filter(|id| !referenced.contains(id)).collect();
+        heads.sort_unstable();
+
+        let mut report = ChainReport {
+            heads,
+            duplicate_ids,
+            ..Default::default()
+        };
+
+        let mut visited: HashSet<u8> = HashSet::new();
+
+        if let Some(&head) = report.heads.first() {
+            walk_chain(head, &by_id, &mut visited, &mut report);
+        }
+
+        // A pipeline that's entirely a cycle has every node referenced as
+        // someone's `next_id`, so no head exists and the walk above never
+        // runs. Walk whatever's left so those head-less cycles still surface
+        // instead of silently reporting an empty chain.
+        let mut unvisited: Vec<u8> = by_id.keys().copied().filter(|id| !visited.contains(id)).collect();
+        unvisited.sort_unstable();
+        for id in unvisited {
+            if !visited.contains(&id) {
+                walk_chain(id, &by_id, &mut visited, &mut report);
+            }
+        }
+
+        report
+    }
+}
+
+fn walk_chain(start: u8, by_id: &HashMap<u8, &crate::Message>, visited: &mut HashSet<u8>, report: &mut ChainReport) {
+    let mut path: Vec<u8> = Vec::new();
+    let mut current = Some(start);
+    while let Some(id) = current {
+        if let Some(pos) = path.iter().position(|&seen| seen == id) {
+            report.cycles.push(path[pos..].to_vec());
+            break;
+        }
+        let Some(msg) = by_id.get(&id) else {
+            break;
+        };
+        path.push(id);
+        visited.insert(id);
+        report.ordered_ids.push(id);
+        report.ordered_bodies.push(msg.body.clone());
+        current = match msg.next_id {
+            None => None,
+            Some(next) if by_id.contains_key(&next) => Some(next),
+            Some(next) => {
+                report.gaps.push((id, next));
+                None
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    fn msg(id: u8, body: &str, next_id: Option<u8>) -> Message {
+        Message { id, body: body.as_bytes().to_vec(), next_id }
+    }
+
+    #[test_log::test]
+    fn test_chain_reconstructs_in_next_id_order() {
+        let mut pipeline = Pipeline::new(1);
+        pipeline.message.push(msg(2, "b", Some(3)));
+        pipeline.message.push(msg(1, "a", Some(2)));
+        pipeline.message.push(msg(3, "c", None));
+
+        let report = pipeline.reconstruct_chain();
+        assert_eq!(report.heads, vec![1]);
+        assert_eq!(report.ordered_ids, vec![1, 2, 3]);
+        assert!(report.gaps.is_empty());
+        assert!(report.cycles.is_empty());
+        assert!(report.duplicate_ids.is_empty());
+    }
+
+    #[test_log::test]
+    fn test_gap_is_reported_when_next_id_is_missing() {
+        let mut pipeline = Pipeline::new(1);
+        pipeline.message.push(msg(1, "a", Some(2)));
+
+        let report = pipeline.reconstruct_chain();
+        assert_eq!(report.gaps, vec![(1, 2)]);
+    }
+
+    #[test_log::test]
+    fn test_duplicate_ids_are_reported() {
+        let mut pipeline = Pipeline::new(1);
+        pipeline.message.push(msg(1, "a", None));
+        pipeline.message.push(msg(1, "a-again", None));
+
+        let report = pipeline.reconstruct_chain();
+        assert_eq!(report.duplicate_ids, vec![1]);
+    }
+
+    #[test_log::test]
+    fn test_headless_cycle_is_still_detected() {
+        // Every message is someone's next_id, so there is no head at all.
+        let mut pipeline = Pipeline::new(1);
+        pipeline.message.push(msg(1, "a", Some(2)));
+        pipeline.message.push(msg(2, "b", Some(1)));
+
+        let report = pipeline.reconstruct_chain();
+        assert!(report.heads.is_empty());
+        assert_eq!(report.cycles.len(), 1);
+        assert_eq!(report.cycles[0].len(), 2);
+    }
+}
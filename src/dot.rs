@@ -0,0 +1,98 @@
+//! Graphviz DOT export of the message chains tracked by [`Pipelines`].
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::{Pipeline, Pipelines};
+
+impl Pipelines {
+    /// Emits a `digraph` with one cluster per [`PipelineId`](crate::PipelineId), a
+    /// node per message (labeled with its id and decoded body) and `id -> next_id`
+    /// edges following the chain. Closed pipelines are filled grey, and a `next_id`
+    /// that points at a message we never received gets a dashed red placeholder
+    /// node and edge, so a broken or incomplete chain is visible at a glance.
+    pub fn to_dot<W: Write>(&self, writer: &mut W) -> std::fmt::Result {
+        writeln!(writer, "digraph pipelines {{")?;
+        writeln!(writer, "  node [shape=box];")?;
+        let mut keys = self.inner.keys().collect::<Vec<_>>();
+        keys.sort_unstable();
+        for key in keys {
+            write_cluster(writer, &self.inner[key])?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+fn write_cluster<W: Write>(writer: &mut W, pipeline: &Pipeline) -> std::fmt::Result {
+    let known_ids: HashSet<u8> = pipeline.message.iter().map(|msg| msg.id).collect();
+
+    writeln!(writer, "  subgraph cluster_{} {{", pipeline.id)?;
+    writeln!(
+        writer,
+        "    label = \"Pipeline {}{}\";",
+        pipeline.id,
+        if pipeline.closed { " (closed)" } else { "" }
+    )?;
+    if pipeline.closed {
+        writeln!(writer, "    style = filled;")?;
+        writeln!(writer, "    fillcolor = \"#eeeeee\";")?;
+    }
+
+    for msg in pipeline.message.iter() {
+        writeln!(
+            writer,
+            "    \"{}_{}\" [label=\"{}\\n{}\"];",
+            pipeline.id,
+            msg.id,
+            msg.id,
+            escape_label(&msg.display_body())
+        )?;
+    }
+
+    for msg in pipeline.message.iter() {
+        let Some(next_id) = msg.next_id else { continue };
+        if known_ids.contains(&next_id) {
+            writeln!(writer, "    \"{0}_{1}\" -> \"{0}_{2}\";", pipeline.id, msg.id, next_id)?;
+        } else {
+            writeln!(
+                writer,
+                "    \"{0}_missing_{1}\" [label=\"{1}\\n(missing)\", style=dashed, color=red];",
+                pipeline.id, next_id
+            )?;
+            writeln!(
+                writer,
+                "    \"{0}_{1}\" -> \"{0}_missing_{2}\" [style=dashed, color=red];",
+                pipeline.id, msg.id, next_id
+            )?;
+        }
+    }
+
+    writeln!(writer, "  }}")
+}
+
+fn escape_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParsedMessage, PipelinesConfig};
+
+    #[test_log::test]
+    fn test_to_dot_emits_a_cluster_per_pipeline_with_edges_and_a_missing_node() {
+        let mut pipelines = Pipelines::new(PipelinesConfig::default());
+        for line in ["1 1 0 a 2", "1 2 0 b 9"] {
+            pipelines.insert_message(ParsedMessage::parse(line).expect("parses"));
+        }
+
+        let mut out = String::new();
+        pipelines.to_dot(&mut out).expect("writing to a String cannot fail");
+
+        assert!(out.starts_with("digraph pipelines {"));
+        assert!(out.contains("subgraph cluster_1 {"));
+        assert!(out.contains("\"1_1\" -> \"1_2\";"));
+        assert!(out.contains("\"1_missing_9\""));
+        assert!(out.contains("style=dashed, color=red"));
+    }
+}
@@ -3,16 +3,30 @@ use std::collections::{BinaryHeap, HashMap};
 use std::fmt::{Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::io;
-use std::io::{BufRead};
-use std::str::FromStr;
+use std::io::Read;
+use std::path::Path;
 use anyhow::{anyhow, Error};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, Level};
 
+mod chain;
+mod dot;
+mod parsing;
+mod source;
+
+use source::{serve_tcp, BufReadSource, run_sync};
+
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Encoding {
     Ascii = 0,
     Hex = 1,
+    Base64 = 2,
+    /// Gzip-compressed bytes, themselves transported hex-encoded since the
+    /// wire format is line-oriented text.
+    Gzip = 3,
 }
 
 impl TryFrom<u8> for Encoding {
@@ -22,37 +36,111 @@ impl TryFrom<u8> for Encoding {
         Ok(match value {
             0 => Self::Ascii,
             1 => Self::Hex,
+            2 => Self::Base64,
+            3 => Self::Gzip,
             _ => { return Err(anyhow!("Not a valid encoding")); }
         })
     }
 }
 
+/// Caps how much a single `Gzip`-encoded message may decompress to, so a
+/// highly-compressible payload can't be used as a decompression bomb.
+const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
 impl Encoding {
-    pub fn decode(&self, msg: String) -> anyhow::Result<String> {
+    /// Decodes a wire-format token into the message's raw bytes. Operating on
+    /// bytes (rather than requiring valid UTF-8 up front) lets binary payloads
+    /// survive; presentation happens separately, at display time.
+    pub fn decode(&self, msg: &[u8]) -> anyhow::Result<Vec<u8>> {
         Ok(match self {
-            Encoding::Ascii => {
-                msg
-            }
-            Encoding::Hex => {
-                String::from_utf8(hex::decode(msg.as_str())?)?
+            Encoding::Ascii => msg.to_vec(),
+            Encoding::Hex => hex::decode(msg)?,
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.decode(msg)?,
+            Encoding::Gzip => {
+                let compressed = hex::decode(msg)?;
+                let decoder = flate2::read::GzDecoder::new(compressed.as_slice());
+                let mut decoded = Vec::new();
+                decoder.take(MAX_DECOMPRESSED_SIZE as u64 + 1).read_to_end(&mut decoded)?;
+                if decoded.len() as u64 > MAX_DECOMPRESSED_SIZE as u64 {
+                    return Err(anyhow!("Decompressed message exceeds the {MAX_DECOMPRESSED_SIZE} byte limit"));
+                }
+                decoded
             }
         })
     }
 }
 
+#[cfg(test)]
+mod encoding_tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_base64_round_trip() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(b"hello");
+        let decoded = Encoding::Base64.decode(encoded.as_bytes()).expect("decodes");
+        assert_eq!(decoded, b"hello");
+    }
+
+    #[test_log::test]
+    fn test_gzip_round_trip() {
+        use std::io::Write as _;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"hello gzip").unwrap();
+        let hex_encoded = hex::encode(encoder.finish().unwrap());
+
+        let decoded = Encoding::Gzip.decode(hex_encoded.as_bytes()).expect("decodes");
+        assert_eq!(decoded, b"hello gzip");
+    }
+
+    #[test_log::test]
+    fn test_gzip_over_the_size_limit_is_rejected() {
+        use std::io::Write as _;
+
+        let payload = vec![0u8; MAX_DECOMPRESSED_SIZE + 1024];
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&payload).unwrap();
+        let hex_encoded = hex::encode(encoder.finish().unwrap());
+
+        let err = Encoding::Gzip
+            .decode(hex_encoded.as_bytes())
+            .expect_err("a decompression bomb must be rejected, not decoded");
+        assert!(err.to_string().contains("exceeds"));
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     id: u8,
-    body: String,
+    body: Vec<u8>,
+    next_id: Option<u8>,
+}
+
+impl Message {
+    /// Renders `body` as text when it's valid UTF-8, falling back to hex so
+    /// binary payloads still print something useful.
+    fn display_body(&self) -> String {
+        display_bytes(&self.body)
+    }
+}
+
+/// Renders bytes as text when they're valid UTF-8, falling back to hex so
+/// binary payloads still print something useful.
+fn display_bytes(body: &[u8]) -> String {
+    match std::str::from_utf8(body) {
+        Ok(text) => text.to_string(),
+        Err(_) => hex::encode(body),
+    }
 }
 
-impl TryFrom<(u8, Encoding, String)> for Message {
+impl TryFrom<(u8, Encoding, String, Option<u8>)> for Message {
     type Error = Error;
 
-    fn try_from((id, encoding, msg): (u8, Encoding, String)) -> Result<Self, Self::Error> {
+    fn try_from((id, encoding, msg, next_id): (u8, Encoding, String, Option<u8>)) -> Result<Self, Self::Error> {
         Ok(Message {
             id,
-            body: encoding.decode(msg)?,
+            body: encoding.decode(msg.as_bytes())?,
+            next_id,
         })
     }
 }
@@ -81,47 +169,13 @@ impl Ord for Message {
 pub struct ParsedMessage {
     pipeline_id: u8,
     id: u8,
-    encoding: Encoding,
+    /// `None` when the line spells the encoding field as `-`, meaning "use
+    /// whatever `PipelinesConfig::default_input_encoding` says".
+    encoding: Option<Encoding>,
     message: String,
     next_id: Option<u8>,
 }
 
-impl ParsedMessage {
-    fn parse(line: &str) -> anyhow::Result<Self> {
-        let mut tokens = line.split(" ");
-
-        let pipeline_id = tokens.next().ok_or(anyhow::anyhow!("Missing pipeline id"))?;
-        let pipeline_id = u8::from_str(pipeline_id)?;
-
-        let id = tokens.next().ok_or(anyhow::anyhow!("Missing id"))?;
-        let id = u8::from_str(id)?;
-
-        let encoding = tokens.next().ok_or(anyhow::anyhow!("Missing id"))?;
-        let encoding: Encoding = u8::from_str(encoding)?.try_into()?;
-
-        let message = tokens.next().ok_or(anyhow::anyhow!("Missing msg"))?;
-        let message = message.to_string();
-
-        let next_id = tokens.next().ok_or(anyhow::anyhow!("Missing next_id"))?;
-        let next_id = i16::from_str(next_id)?;
-        let next_id = match next_id {
-            -1 => None,
-            x if x >= 0 && x <= u8::MAX as i16 => Some(x as u8),
-            _ => {
-                return Err(anyhow!("Incorrect next id {}",next_id));
-            }
-        };
-
-        Ok(Self {
-            pipeline_id,
-            id,
-            encoding,
-            message,
-            next_id,
-        })
-    }
-}
-
 pub type PipelineId = u8;
 
 #[derive(Default, Debug, Clone)]
@@ -146,9 +200,72 @@ impl Pipeline {
         }
     }
 }
-#[derive(Default, Clone)]
-pub struct PipelinesConfig{
-    pub discard_invalid_next_id:bool,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSortOrder {
+    /// Display messages sorted by ascending id.
+    ById,
+    /// Display messages in the order implied by the `next_id` chain.
+    ByNextId,
+}
+
+impl Default for OutputSortOrder {
+    fn default() -> Self {
+        OutputSortOrder::ById
+    }
+}
+
+/// Stamped onto a config document that predates the `version` field, so
+/// `migrate` can tell a genuinely old file from one already on
+/// [`PipelinesConfig::CURRENT_VERSION`]. Must never equal `CURRENT_VERSION`.
+fn legacy_version_sentinel() -> String {
+    "0".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelinesConfig {
+    #[serde(default = "legacy_version_sentinel")]
+    pub version: String,
+    pub discard_invalid_next_id: bool,
+    pub default_input_encoding: Encoding,
+    pub drop_messages_on_closed_pipeline: bool,
+    pub output_sort_order: OutputSortOrder,
+}
+
+impl PipelinesConfig {
+    pub const CURRENT_VERSION: &'static str = "2";
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path.as_ref())?;
+        let mut config: PipelinesConfig = toml::from_str(&contents)?;
+        config.migrate();
+        Ok(config)
+    }
+
+    /// Upgrades a config loaded from an older schema version in place, defaulting
+    /// any field that didn't exist yet. Missing fields are already defaulted by
+    /// `#[serde(default)]` during deserialization (a document that omits `version`
+    /// entirely deserializes to [`legacy_version_sentinel`], not `CURRENT_VERSION`),
+    /// so this only needs to bump the stamped version once the upgrade is done.
+    fn migrate(&mut self) {
+        if self.version != Self::CURRENT_VERSION {
+            debug!("Migrating pipelines config from version {:?} to {}", self.version, Self::CURRENT_VERSION);
+            self.version = Self::CURRENT_VERSION.to_string();
+        }
+    }
+}
+
+impl Default for PipelinesConfig {
+    fn default() -> Self {
+        Self {
+            version: Self::CURRENT_VERSION.to_string(),
+            discard_invalid_next_id: false,
+            default_input_encoding: Encoding::Ascii,
+            drop_messages_on_closed_pipeline: true,
+            output_sort_order: OutputSortOrder::ById,
+        }
+    }
 }
 
 #[derive(Default, Clone)]
@@ -168,8 +285,18 @@ impl Pipelines {
                 }
                 Some(pipeline) => {
                     writeln!(writer, "Pipeline:{}", pipeline.id)?;
-                    for msg in pipeline.message.clone().into_sorted_vec() {
-                        writeln!(writer, "\t{}| {}", msg.id, msg.body)?;
+                    match self.config.output_sort_order {
+                        OutputSortOrder::ById => {
+                            for msg in pipeline.message.clone().into_sorted_vec() {
+                                writeln!(writer, "\t{}| {}", msg.id, msg.display_body())?;
+                            }
+                        }
+                        OutputSortOrder::ByNextId => {
+                            let report = pipeline.reconstruct_chain();
+                            for (id, body) in report.ordered_ids.iter().zip(report.ordered_bodies.iter()) {
+                                writeln!(writer, "\t{}| {}", id, display_bytes(body))?;
+                            }
+                        }
                     }
                 }
             }
@@ -196,8 +323,11 @@ impl Pipelines {
         let pipeline = self.inner.entry(msg.pipeline_id)
             .or_insert(Pipeline::new(msg.pipeline_id));
         if pipeline.closed {
-            debug!("The following message was ignored because the pipeline was closed: {msg:?}");
-            return;
+            if self.config.drop_messages_on_closed_pipeline {
+                debug!("The following message was dropped because the pipeline was closed: {msg:?}");
+                return;
+            }
+            error!("The following message was accepted even though the pipeline was closed: {msg:?}");
         }
         if let Some(next_id) = &pipeline.next_id {
             if msg.id != *next_id && self.config.discard_invalid_next_id {
@@ -205,7 +335,8 @@ impl Pipelines {
                 return;
             }
         }
-        match (msg.id, msg.encoding, msg.message).try_into() {
+        let encoding = msg.encoding.unwrap_or(self.config.default_input_encoding);
+        match (msg.id, encoding, msg.message, msg.next_id).try_into() {
             Ok(msg) => pipeline.message.push(msg),
             Err(e) => {
                 debug!("Message is not valid {e:?}");
@@ -225,17 +356,41 @@ fn main() {
         .with_max_level(Level::TRACE)
         .compact()
         .init();
-    let stdin = io::stdin();
-    let mut lines = stdin.lock().lines();
-    let mut pipelines = Pipelines::new(PipelinesConfig::default());
-    while let Some(Ok(line)) = lines.next() {
-        if line.is_empty() {
-            break;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let listen_addr = args.iter().position(|a| a == "--listen").and_then(|i| args.get(i + 1)).cloned();
+    let dot = args.iter().any(|a| a == "--dot");
+    let config_path = args
+        .iter()
+        .find(|a| a.as_str() != "--listen" && a.as_str() != "--dot" && listen_addr.as_deref() != Some(a.as_str()));
+
+    let config = match config_path {
+        Some(path) => PipelinesConfig::from_file(path).unwrap_or_else(|e| {
+            error!("Could not load config from {path}: {e:?}, falling back to defaults");
+            PipelinesConfig::default()
+        }),
+        None => PipelinesConfig::default(),
+    };
+    let mut pipelines = Pipelines::new(config);
+
+    match listen_addr {
+        // Long-lived daemon mode: feed pipelines from TCP connections instead of stdin.
+        Some(addr) => {
+            let result = tokio::runtime::Runtime::new()
+                .expect("failed to start the tokio runtime")
+                .block_on(serve_tcp(&addr, &mut pipelines));
+            if let Err(e) = result {
+                error!("Error serving on {addr}: {e:?}");
+            }
         }
-        match ParsedMessage::parse(line.as_str()) {
-            Ok(msg) => { pipelines.insert_message(msg); }
-            Err(err) => {
-                debug!("Could not parse line `{line}` with err: {err:?}");
+        None => {
+            let source = BufReadSource::new(io::stdin().lock());
+            if let Err(e) = run_sync(source, &mut pipelines) {
+                error!("Error reading from stdin: {e:?}");
+            }
+            if dot {
+                let mut out = String::new();
+                pipelines.to_dot(&mut out).expect("writing to a String cannot fail");
+                println!("{out}");
             }
         }
     }
@@ -280,6 +435,7 @@ err
 "#;
     let mut pipelines = Pipelines::new(PipelinesConfig{
         discard_invalid_next_id:false,
+        ..Default::default()
     });
     for line in lines.lines() {
         match ParsedMessage::parse(line) {
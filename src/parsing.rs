@@ -0,0 +1,195 @@
+//! Combinator-based parser for the wire format consumed by [`ParsedMessage::parse`].
+//!
+//! A line is `pipeline_id id encoding body next_id`, fields separated by a single
+//! space. `encoding` is either a numeric [`Encoding`] discriminant or `-`, meaning
+//! "use `PipelinesConfig::default_input_encoding`". `body` is either a bareword
+//! (no whitespace) or a double-quoted string with `\"`, `\\`, `\n` and `\t` escapes, so ASCII
+//! bodies may contain spaces. Anything left over after `next_id` is a parse error
+//! rather than being silently discarded.
+
+use combine::error::StreamError;
+use combine::parser::char::{char, digit};
+use combine::parser::sequence::between;
+use combine::stream::position::{self, IndexPositioner};
+use combine::stream::StreamErrorFor;
+use combine::{choice, eof, many, many1, none_of, satisfy, EasyParser, ParseError, Parser, Stream};
+
+use crate::{Encoding, ParsedMessage};
+
+fn uint<Input>() -> impl Parser<Input, Output = u64>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    many1(digit()).and_then(|digits: String| {
+        digits
+            .parse::<u64>()
+            .map_err(|_| StreamErrorFor::<Input>::message_static_message("number is too large"))
+    })
+}
+
+fn u8_field<Input>() -> impl Parser<Input, Output = u8>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    uint().and_then(|n: u64| {
+        u8::try_from(n).map_err(|_| StreamErrorFor::<Input>::message_static_message("value does not fit in a u8"))
+    })
+}
+
+/// `None` means the line spelled the encoding field as `-`, asking for
+/// `PipelinesConfig::default_input_encoding` instead of naming one.
+fn encoding<Input>() -> impl Parser<Input, Output = Option<Encoding>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((
+        char('-').map(|_| None),
+        u8_field()
+            .and_then(|n: u8| {
+                Encoding::try_from(n).map_err(|_| StreamErrorFor::<Input>::message_static_message("not a valid encoding"))
+            })
+            .map(Some),
+    ))
+}
+
+fn next_id<Input>() -> impl Parser<Input, Output = Option<u8>>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((char('-').with(char('1')).map(|_| None), u8_field().map(Some)))
+}
+
+fn escaped_char<Input>() -> impl Parser<Input, Output = char>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((
+        char('\\').with(choice((
+            char('"').map(|_| '"'),
+            char('\\').map(|_| '\\'),
+            char('n').map(|_| '\n'),
+            char('t').map(|_| '\t'),
+        ))),
+        none_of(['"', '\\'].iter().copied()),
+    ))
+}
+
+fn quoted_body<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    between(char('"'), char('"'), many(escaped_char()))
+}
+
+fn bareword_body<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    many1(satisfy(|c: char| !c.is_whitespace()))
+}
+
+fn body<Input>() -> impl Parser<Input, Output = String>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((quoted_body(), bareword_body())).expected("a bareword or a double-quoted string")
+}
+
+fn sep<Input>() -> impl Parser<Input, Output = char>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    char(' ').expected("a single space separating fields")
+}
+
+fn message<Input>() -> impl Parser<Input, Output = ParsedMessage>
+where
+    Input: Stream<Token = char>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        u8_field().expected("a pipeline id"),
+        sep(),
+        u8_field().expected("a message id"),
+        sep(),
+        encoding().expected("an encoding (0 = ascii, 1 = hex, 2 = base64, 3 = gzip), or - for the configured default"),
+        sep(),
+        body(),
+        sep(),
+        next_id().expected("a next id, or -1 to close the pipeline"),
+        eof().expected("end of line (trailing tokens are rejected, not discarded)"),
+    )
+        .map(|(pipeline_id, _, id, _, encoding, _, message, _, next_id, _)| ParsedMessage {
+            pipeline_id,
+            id,
+            encoding,
+            message,
+            next_id,
+        })
+}
+
+impl ParsedMessage {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        // Indexed rather than line/column positioning so a failure reports the
+        // byte offset into `line`, which is what a caller needs to point at the
+        // bad token (the wire format is ASCII, so byte offset and char index agree).
+        let stream = position::Stream::with_positioner(line, IndexPositioner::new());
+        message()
+            .easy_parse(stream)
+            .map(|(msg, _)| msg)
+            .map_err(|err| anyhow::anyhow!("failed to parse message at byte {}: {}", err.position, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test]
+    fn test_quoted_body_allows_spaces_and_escapes() {
+        let msg = ParsedMessage::parse(r#"1 2 0 "hello \"world\"" 3"#).expect("parses");
+        assert_eq!(msg.pipeline_id, 1);
+        assert_eq!(msg.id, 2);
+        assert_eq!(msg.encoding, Some(Encoding::Ascii));
+        assert_eq!(msg.message, "hello \"world\"");
+        assert_eq!(msg.next_id, Some(3));
+    }
+
+    #[test_log::test]
+    fn test_bareword_body() {
+        let msg = ParsedMessage::parse("1 2 0 some_text 3").expect("parses");
+        assert_eq!(msg.message, "some_text");
+    }
+
+    #[test_log::test]
+    fn test_dash_encoding_defers_to_the_configured_default() {
+        let msg = ParsedMessage::parse("1 2 - some_text 3").expect("parses");
+        assert_eq!(msg.encoding, None);
+    }
+
+    #[test_log::test]
+    fn test_trailing_tokens_are_rejected_not_discarded() {
+        let err = ParsedMessage::parse("1 0 0 message_10 1 This text should be ignored")
+            .expect_err("trailing tokens must fail to parse, not be silently ignored");
+        assert!(err.to_string().contains("byte"));
+    }
+
+    #[test_log::test]
+    fn test_invalid_encoding_is_rejected() {
+        assert!(ParsedMessage::parse("1 2 9 some_text 3").is_err());
+    }
+
+    #[test_log::test]
+    fn test_missing_fields_are_rejected() {
+        assert!(ParsedMessage::parse("1 2 0").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Pluggable ingestion for [`Pipelines`]: the synchronous [`MessageSource`] trait
+//! keeps the original stdin-filter behaviour working, while [`AsyncMessageSource`]
+//! plus [`run_async`] let the crate be driven as a long-lived daemon reading
+//! framed lines from a file or a TCP socket instead.
+
+use std::io;
+use std::io::BufRead;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+use tracing::debug;
+
+use crate::{ParsedMessage, Pipelines};
+
+/// A synchronous source of newline-delimited messages.
+pub trait MessageSource {
+    fn next_line(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Adapts any [`BufRead`] (stdin, a file, ...) into a [`MessageSource`].
+pub struct BufReadSource<R> {
+    lines: io::Lines<R>,
+}
+
+impl<R: BufRead> BufReadSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: reader.lines() }
+    }
+}
+
+impl<R: BufRead> MessageSource for BufReadSource<R> {
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        self.lines.next().transpose()
+    }
+}
+
+/// Feeds `pipelines` from `source` until it runs out of lines or yields an
+/// empty one, mirroring the original stdin loop in `main`.
+pub fn run_sync<S: MessageSource>(mut source: S, pipelines: &mut Pipelines) -> io::Result<()> {
+    while let Some(line) = source.next_line()? {
+        if line.is_empty() {
+            break;
+        }
+        match ParsedMessage::parse(&line) {
+            Ok(msg) => pipelines.insert_message(msg),
+            Err(err) => debug!("Could not parse line `{line}` with err: {err:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// The async counterpart of [`MessageSource`], for sources backed by a tokio
+/// stream (a TCP socket, a pipe) rather than a blocking reader.
+#[async_trait]
+pub trait AsyncMessageSource {
+    async fn next_line(&mut self) -> io::Result<Option<String>>;
+}
+
+/// Adapts any [`AsyncRead`] stream (`TcpStream`, a file opened via
+/// `tokio::fs`, ...) into an [`AsyncMessageSource`].
+pub struct AsyncLineSource<R> {
+    lines: Lines<BufReader<R>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncLineSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: BufReader::new(reader).lines() }
+    }
+}
+
+#[async_trait]
+impl<R: AsyncRead + Unpin + Send> AsyncMessageSource for AsyncLineSource<R> {
+    async fn next_line(&mut self) -> io::Result<Option<String>> {
+        self.lines.next_line().await
+    }
+}
+
+/// Drives `pipelines` from `source` until the stream closes (`next_line`
+/// returns `Ok(None)`) or yields an empty line, at which point the loop shuts
+/// down gracefully rather than treating the end of the stream as an error.
+pub async fn run_async<S: AsyncMessageSource>(mut source: S, pipelines: &mut Pipelines) -> io::Result<()> {
+    while let Some(line) = source.next_line().await? {
+        if line.is_empty() {
+            break;
+        }
+        match ParsedMessage::parse(&line) {
+            Ok(msg) => pipelines.insert_message(msg),
+            Err(err) => debug!("Could not parse line `{line}` with err: {err:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// Runs `pipelines` as a long-lived daemon, accepting TCP connections on
+/// `addr` and feeding each one through [`run_async`] until the client closes
+/// the connection or sends an empty line, then waiting for the next one.
+pub async fn serve_tcp(addr: &str, pipelines: &mut Pipelines) -> io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    debug!("Listening for messages on {addr}");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        debug!("Accepted a connection from {peer}");
+        run_async(AsyncLineSource::new(stream), pipelines).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PipelinesConfig;
+
+    #[tokio::test]
+    async fn test_run_async_feeds_pipelines_until_the_stream_closes() {
+        let data = b"1 0 0 hello 1\n1 1 0 world -1\n";
+        let source = AsyncLineSource::new(&data[..]);
+        let mut pipelines = Pipelines::new(PipelinesConfig::default());
+
+        run_async(source, &mut pipelines).await.expect("the stream closes gracefully");
+
+        let mut out = String::new();
+        pipelines.display(&mut out).expect("works");
+        assert!(out.contains("hello"));
+        assert!(out.contains("world"));
+    }
+
+    #[tokio::test]
+    async fn test_run_async_stops_at_an_empty_line() {
+        let data = b"1 0 0 hello 1\n\n1 1 0 should_not_be_read -1\n";
+        let source = AsyncLineSource::new(&data[..]);
+        let mut pipelines = Pipelines::new(PipelinesConfig::default());
+
+        run_async(source, &mut pipelines).await.expect("works");
+
+        let mut out = String::new();
+        pipelines.display(&mut out).expect("works");
+        assert!(out.contains("hello"));
+        assert!(!out.contains("should_not_be_read"));
+    }
+}